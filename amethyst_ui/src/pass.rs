@@ -278,15 +278,31 @@ impl<'a> ParallelIterator for DrawUiApply<'a> {
                         effect.clear();
                     }
 
-                    if let Some(image) = ui_text
-                        .get(entity)
-                        .and_then(|ref ui_text| ui_text.texture.as_ref())
-                        .and_then(|texture| tex_storage.get(texture))
-                    {
-                        effect.data.textures.push(image.view().clone());
-                        effect.data.samplers.push(image.sampler().clone());
-                        effect.draw(mesh.slice(), encoder);
-                        effect.clear();
+                    // Glyph quads are already positioned in entity-local pixel space, so
+                    // their own mesh is drawn in place of the unit quad, with `dimension`
+                    // set to 1 to disable the unit quad's 0..1 -> pixel-size scaling.
+                    if let Some(ui_text) = ui_text.get(entity) {
+                        if let (Some(glyph_mesh), Some(texture)) =
+                            (ui_text.mesh.as_ref(), ui_text.texture.as_ref())
+                        {
+                            if let (Some(glyph_mesh), Some(atlas)) =
+                                (mesh_storage.get(glyph_mesh), tex_storage.get(texture))
+                            {
+                                if let Some(vbuf) = glyph_mesh.buffer(PosTex::ATTRIBUTES) {
+                                    let glyph_args = VertexArgs {
+                                        proj_vec: proj_vec.into(),
+                                        coord: [ui_transform.x, ui_transform.y],
+                                        dimension: [1., 1.],
+                                    };
+                                    effect.update_constant_buffer("VertexArgs", &glyph_args, encoder);
+                                    effect.data.vertex_bufs.push(vbuf.clone());
+                                    effect.data.textures.push(atlas.view().clone());
+                                    effect.data.samplers.push(atlas.sampler().clone());
+                                    effect.draw(glyph_mesh.slice(), encoder);
+                                    effect.clear();
+                                }
+                            }
+                        }
                     }
                 }
             }))
@@ -1,17 +1,675 @@
+use std::collections::HashMap;
+
 use amethyst_assets::{AssetStorage, Loader};
-use amethyst_renderer::{Texture, TextureData, TextureHandle, TextureMetadata};
+use amethyst_renderer::{Mesh, MeshHandle, PosTex, Texture, TextureData, TextureHandle,
+                         TextureMetadata};
 use gfx::format::{ChannelType, SurfaceType};
-use rusttype::{Point, Scale};
-use specs::{Component, DenseVecStorage, Fetch, Join, ReadStorage, System, WriteStorage};
+use rusttype::{GlyphId, Point, PositionedGlyph, Scale};
+use specs::{Component, DenseVecStorage, Entities, Fetch, FetchMut, FlaggedStorage, Index, Join,
+            ReaderId, ReadStorage, System, WriteStorage};
+use specs::storage::ComponentEvent;
 use unicode_normalization::UnicodeNormalization;
 use unicode_normalization::char::is_combining_mark;
 
 use super::*;
 
+/// Initial width/height, in texels, of the shared glyph atlas. The atlas doubles in size
+/// whenever a freshly rasterized glyph no longer fits.
+const ATLAS_START_SIZE: u32 = 512;
+
+/// The atlas won't grow past this size; once hit, glyphs that are no longer referenced by
+/// any live `UiText` are evicted to make room instead.
+const ATLAS_MAX_SIZE: u32 = 4096;
+
+/// How a glyph's rasterized coverage is turned into the atlas's stored alpha.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum FontRenderMode {
+    /// Hard 0/1 edge at 50% coverage, for crisp pixel-art-style UIs.
+    Mono,
+    /// Gamma-corrected coverage used as-is for the alpha channel.
+    Alpha,
+    /// Same gamma-corrected coverage as `Alpha`.
+    // TODO: true three-channel LCD subpixel compositing needs a per-channel atlas and a
+    // shader that samples R/G/B separately; until then this renders identically to `Alpha`.
+    SubpixelAA,
+}
+
+/// Identifies a single rasterized glyph: a specific glyph of a specific font, at a
+/// specific size, subpixel offset, render mode and gamma correction. Any `UiText` that
+/// needs the exact same combination reuses the same atlas slot instead of re-rasterizing
+/// it; anything that would change the stored bytes (including the render mode or gamma
+/// settings) gets its own slot.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: FontHandle,
+    glyph: GlyphId,
+    size_bits: u32,
+    subpixel: (u8, u8),
+    mode: FontRenderMode,
+    contrast_bits: u32,
+    gamma_bits: u32,
+    luminance_bucket: u8,
+    embolden_bits: u32,
+    skew_bits: u32,
+}
+
+/// A rectangle within the atlas texture, in texels.
+#[derive(Copy, Clone, Debug)]
+struct AtlasRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    /// How far the stored bitmap's top-left corner was padded outward from the glyph's
+    /// own `pixel_bounding_box`, e.g. to leave room for synthetic embolden/skew. Quad
+    /// positions need to shift by this amount to stay aligned with the rest of the line.
+    offset_x: f32,
+    offset_y: f32,
+}
+
+/// A glyph currently resident in the atlas.
+struct AtlasEntry {
+    rect: AtlasRect,
+    /// Number of live `UiText`s whose current layout references this glyph. Entries with
+    /// a count of zero are free to be reclaimed the next time the atlas runs out of room.
+    ref_count: u32,
+}
+
+/// Default gamma correction exponent, chosen to undo the roughly 2.2 display gamma so
+/// anti-aliased edges don't look thin or "crunchy" against the background.
+const DEFAULT_GAMMA: f32 = 1.0 / 2.2;
+
+/// Default contrast multiplier; 1.0 leaves `DEFAULT_GAMMA` unmodified.
+const DEFAULT_CONTRAST: f32 = 1.0;
+
+/// Number of discrete foreground-luminance buckets the gamma correction is tuned for.
+/// Coarse bucketing (rather than a continuous per-glyph correction) keeps the atlas cache
+/// from exploding into one copy of every glyph per distinct text color.
+const LUMINANCE_BUCKETS: u8 = 4;
+
+/// A precomputed coverage -> corrected-coverage lookup, built once per distinct
+/// `(contrast, gamma, luminance bucket)` combination and cached on the atlas. Mirrors
+/// WebRender's `gamma_lut`: anti-aliased glyph coverage looks thin on a gamma-encoded
+/// display unless it's re-exponentiated before being used as alpha, and the ideal exponent
+/// shifts a little with how bright the text itself is.
+#[derive(Clone)]
+struct GammaLut {
+    table: [u8; 256],
+}
+
+impl GammaLut {
+    fn new(contrast: f32, gamma: f32, luminance: f32) -> Self {
+        // Brighter foreground text gets a smaller exponent, so its coverage is boosted more
+        // and it doesn't look thin; darker text gets a larger one, so it's boosted less and
+        // doesn't look bloated. `1.5 - luminance` keeps the adjustment
+        // centered on `gamma` for mid-tones while leaning it either way at the extremes.
+        let exponent = ((gamma / contrast.max(0.01)) * (1.5 - luminance)).max(0.01);
+        let mut table = [0u8; 256];
+        for (coverage, slot) in table.iter_mut().enumerate() {
+            let v = (coverage as f32 / 255.0).powf(exponent);
+            *slot = (v * 255.0).round().max(0.0).min(255.0) as u8;
+        }
+        GammaLut { table }
+    }
+
+    fn correct(&self, coverage: u8) -> u8 {
+        self.table[coverage as usize]
+    }
+}
+
+/// Buckets a (foreground) color's perceptual luminance into one of `LUMINANCE_BUCKETS`
+/// discrete levels, used to pick which gamma table a glyph is rasterized with.
+fn luminance_bucket(color: [f32; 4]) -> u8 {
+    let luminance = 0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2];
+    ((luminance.max(0.0).min(1.0)) * (LUMINANCE_BUCKETS - 1) as f32).round() as u8
+}
+
+/// Spreads each texel's coverage out to its neighbors within `radius`, approximating a
+/// bolder weight with a cheap max-filter instead of a true multi-pass morphological
+/// dilate.
+fn dilate_coverage(src: &[u8], w: u32, h: u32, radius: u32) -> Vec<u8> {
+    let r = radius as i32;
+    let mut out = vec![0u8; src.len()];
+    for y in 0..h as i32 {
+        for x in 0..w as i32 {
+            let mut covered = 0u8;
+            for dy in -r..=r {
+                let sy = y + dy;
+                if sy < 0 || sy >= h as i32 {
+                    continue;
+                }
+                for dx in -r..=r {
+                    let sx = x + dx;
+                    if sx < 0 || sx >= w as i32 {
+                        continue;
+                    }
+                    covered = covered.max(src[(sy as u32 * w + sx as u32) as usize]);
+                }
+            }
+            out[(y as u32 * w + x as u32) as usize] = covered;
+        }
+    }
+    out
+}
+
+/// A single glyph quad ready to be drawn by `DrawUi`, sampling the shared glyph atlas.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct GlyphQuad {
+    /// Top-left corner of the quad, relative to the owning `UiTransform`'s origin, in
+    /// pixels.
+    pub pos: [f32; 2],
+    /// Size of the quad, in pixels.
+    pub size: [f32; 2],
+    /// Top-left UV coordinate into the atlas texture.
+    pub uv_min: [f32; 2],
+    /// Bottom-right UV coordinate into the atlas texture.
+    pub uv_max: [f32; 2],
+    /// Color the sampled coverage is multiplied by.
+    pub color: [f32; 4],
+}
+
+/// Shared rasterization cache backing every `UiText`, modeled on WebRender's glyph cache /
+/// glyph_brush: each (font, glyph, size, subpixel-offset) combination is rasterized once
+/// into a growable coverage atlas, and every `UiText` that uses it draws a quad sampling
+/// the matching UV rectangle instead of baking its own texture.
+pub struct GlyphAtlas {
+    texture: Option<TextureHandle>,
+    size: u32,
+    buffer: Vec<u8>,
+    glyphs: HashMap<GlyphKey, AtlasEntry>,
+    cursor: (u32, u32),
+    row_height: u32,
+    /// Bounding box, `(min_x, min_y, max_x, max_y)`, of every texel touched since the
+    /// last `sync_texture`, or `None` if nothing has changed. Tracked precisely (rather
+    /// than a plain dirty bool) so a future partial-upload path has the region ready to
+    /// hand; see the note on `sync_texture` for why this crate can't use it yet.
+    dirty_rect: Option<(u32, u32, u32, u32)>,
+    /// Gamma tables, keyed by `(contrast.to_bits(), gamma.to_bits(), luminance_bucket)`,
+    /// built lazily and reused across every glyph that rasterizes with the same settings.
+    gamma_luts: HashMap<(u32, u32, u8), GammaLut>,
+    /// Reader into `UiText`'s component event channel, used to notice entities being
+    /// removed/destroyed so their atlas references get freed. `None` until the first
+    /// `UiTextRenderer::run` call registers it.
+    text_reader: Option<ReaderId<ComponentEvent>>,
+    /// Mirrors each live `UiText`'s `used_glyphs`, keyed by entity index. A
+    /// `ComponentEvent::Removed` only carries the index of the entity whose component is
+    /// already gone, so this is the only place left to find which glyphs it was
+    /// referencing in order to release them.
+    entity_glyphs: HashMap<Index, Vec<GlyphKey>>,
+}
+
+impl Default for GlyphAtlas {
+    fn default() -> Self {
+        GlyphAtlas {
+            texture: None,
+            size: ATLAS_START_SIZE,
+            buffer: vec![0; (ATLAS_START_SIZE * ATLAS_START_SIZE) as usize],
+            glyphs: HashMap::new(),
+            cursor: (0, 0),
+            row_height: 0,
+            dirty_rect: Some((0, 0, ATLAS_START_SIZE, ATLAS_START_SIZE)),
+            gamma_luts: HashMap::new(),
+            text_reader: None,
+            entity_glyphs: HashMap::new(),
+        }
+    }
+}
+
+impl GlyphAtlas {
+    /// The shared atlas texture, once it's been uploaded at least once.
+    pub fn texture(&self) -> Option<&TextureHandle> {
+        self.texture.as_ref()
+    }
+
+    /// Drops one reference from each of `keys`, as if whatever was holding them stopped
+    /// using them this frame.
+    fn release_glyphs(&mut self, keys: &[GlyphKey]) {
+        for key in keys {
+            if let Some(entry) = self.glyphs.get_mut(key) {
+                entry.ref_count = entry.ref_count.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Finds (rasterizing if necessary) the atlas rectangle for `key`, marking it as used
+    /// by the current layout pass. `mode`/`contrast`/`gamma`/`embolden`/`skew` only matter
+    /// the first time a given `key` is seen, since they're baked into the stored coverage
+    /// bytes; `key` already encodes them, so later calls with the same key always hit the
+    /// cache above. Returns `None` if `glyph` has no visible pixels, or if the atlas is
+    /// full and has nothing left to evict to make room for it - callers should treat
+    /// either as "skip drawing this glyph", not as an error.
+    fn rect_for<'f>(
+        &mut self,
+        key: GlyphKey,
+        glyph: &PositionedGlyph<'f>,
+        mode: FontRenderMode,
+        contrast: f32,
+        gamma: f32,
+        embolden: f32,
+        skew: f32,
+    ) -> Option<AtlasRect> {
+        if let Some(entry) = self.glyphs.get(&key) {
+            return Some(entry.rect);
+        }
+        let bb = glyph.pixel_bounding_box()?;
+        let base_w = (bb.max.x - bb.min.x) as u32;
+        let base_h = (bb.max.y - bb.min.y) as u32;
+        let dilate = embolden.max(0.0).round() as u32;
+        let h = base_h + dilate * 2;
+        // Row of the (dilated) bitmap the baseline runs through. The shear below moves
+        // rows above it one way and descender rows below it the other, so pad each side
+        // by the largest offset sheared towards it, measured over the dilated height `h`
+        // the shear loop actually runs over.
+        let baseline_row = glyph.position().y - bb.min.y as f32 + dilate as f32;
+        let above = skew.abs() * baseline_row.max(0.0);
+        let below = skew.abs() * (h as f32 - baseline_row).max(0.0);
+        let (pad_left, pad_right) = if skew < 0.0 { (above, below) } else { (below, above) };
+        let skew_origin = pad_left.ceil() as u32;
+        let w = base_w + dilate * 2 + skew_origin + pad_right.ceil() as u32;
+        let (x, y) = self.alloc(w, h)?;
+
+        // Rasterize into a local scratch buffer so embolden/skew can be applied as a
+        // post-process without disturbing texels already resident in the atlas.
+        let mut scratch = vec![0u8; (w * h) as usize];
+        glyph.draw(|gx, gy, v| {
+            let coverage = (v.max(0.0).min(1.0) * 255.0).round() as u8;
+            let idx = ((gy + dilate) * w + gx + dilate + skew_origin) as usize;
+            scratch[idx] = scratch[idx].max(coverage);
+        });
+        if dilate > 0 {
+            scratch = dilate_coverage(&scratch, w, h, dilate);
+        }
+
+        let lut = match mode {
+            FontRenderMode::Mono => None,
+            FontRenderMode::Alpha | FontRenderMode::SubpixelAA => {
+                Some(self.lut_for(contrast, gamma, key.luminance_bucket))
+            }
+        };
+        for row in 0..h {
+            for col in 0..w {
+                let raw = scratch[(row * w + col) as usize];
+                let corrected = match mode {
+                    FontRenderMode::Mono => if raw >= 128 { 255 } else { 0 },
+                    FontRenderMode::Alpha | FontRenderMode::SubpixelAA => {
+                        lut.as_ref().unwrap().correct(raw)
+                    }
+                };
+                if corrected == 0 {
+                    continue;
+                }
+                // Shear each scanline by an amount proportional to its distance above the
+                // baseline (negative for descender rows below it), so the slant pivots
+                // around the baseline and the baseline row itself stays in place.
+                let shift = (skew * (baseline_row - row as f32)).round() as i32;
+                let dst_col = col as i32 + shift;
+                if dst_col < 0 || dst_col as u32 >= w {
+                    continue;
+                }
+                let idx = ((y + row) * self.size + x + dst_col as u32) as usize;
+                self.buffer[idx] = self.buffer[idx].max(corrected);
+            }
+        }
+        let rect = AtlasRect {
+            x,
+            y,
+            w,
+            h,
+            offset_x: -((dilate + skew_origin) as f32),
+            offset_y: -(dilate as f32),
+        };
+        self.glyphs.insert(key, AtlasEntry { rect, ref_count: 0 });
+        self.mark_dirty(x, y, w, h);
+        Some(rect)
+    }
+
+    /// Expands `dirty_rect` to also cover the `w`x`h` region at `(x, y)`.
+    fn mark_dirty(&mut self, x: u32, y: u32, w: u32, h: u32) {
+        let (x0, y0, x1, y1) = (x, y, x + w, y + h);
+        self.dirty_rect = Some(match self.dirty_rect {
+            Some((ox0, oy0, ox1, oy1)) => (ox0.min(x0), oy0.min(y0), ox1.max(x1), oy1.max(y1)),
+            None => (x0, y0, x1, y1),
+        });
+    }
+
+    /// Returns the gamma table for `(contrast, gamma, luminance_bucket)`, building and
+    /// caching it on first use.
+    fn lut_for(&mut self, contrast: f32, gamma: f32, luminance_bucket: u8) -> GammaLut {
+        let cache_key = (contrast.to_bits(), gamma.to_bits(), luminance_bucket);
+        self.gamma_luts
+            .entry(cache_key)
+            .or_insert_with(|| {
+                let luminance = luminance_bucket as f32 / (LUMINANCE_BUCKETS - 1) as f32;
+                GammaLut::new(contrast, gamma, luminance)
+            })
+            .clone()
+    }
+
+    /// Finds a free `w`x`h` spot using a simple shelf packer, growing or evicting unused
+    /// glyphs as needed to make room. Returns `None` if `w`x`h` can't be made to fit even
+    /// after evicting everything unreferenced at the largest allowed atlas size.
+    fn alloc(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+        loop {
+            if self.cursor.0 + w > self.size {
+                self.cursor = (0, self.cursor.1 + self.row_height);
+                self.row_height = 0;
+            }
+            if self.cursor.1 + h <= self.size {
+                let pos = self.cursor;
+                self.cursor.0 += w;
+                self.row_height = self.row_height.max(h);
+                return Some(pos);
+            }
+            if self.size < ATLAS_MAX_SIZE {
+                self.grow();
+            } else if !self.evict_unreferenced() {
+                // Already at `ATLAS_MAX_SIZE`, and every resident glyph is still
+                // referenced by some live `UiText` - eviction freed nothing, so looping
+                // back round would just hit this same dead end forever. Give up and let
+                // the caller skip this glyph instead of hanging.
+                return None;
+            }
+        }
+    }
+
+    /// Doubles the atlas. Existing glyphs keep their coordinates, since the new buffer is
+    /// simply a larger canvas containing the old one in its top-left corner.
+    fn grow(&mut self) {
+        let old_size = self.size;
+        let old_buffer = self.buffer.clone();
+        self.size *= 2;
+        self.buffer = vec![0; (self.size * self.size) as usize];
+        for y in 0..old_size {
+            let src = (y * old_size) as usize;
+            let dst = (y * self.size) as usize;
+            self.buffer[dst..dst + old_size as usize]
+                .copy_from_slice(&old_buffer[src..src + old_size as usize]);
+        }
+        self.mark_dirty(0, 0, self.size, self.size);
+    }
+
+    /// Drops every glyph no longer referenced by a live `UiText` and repacks the
+    /// survivors tightly, reclaiming the space the dropped glyphs held. Returns `true` if
+    /// anything was actually dropped, so `alloc` can tell a no-op eviction (everything
+    /// resident is still in use) from one that made room.
+    fn evict_unreferenced(&mut self) -> bool {
+        let before = self.glyphs.len();
+        let survivors: Vec<(GlyphKey, AtlasRect, u32)> = self
+            .glyphs
+            .drain()
+            .filter(|&(_, ref entry)| entry.ref_count > 0)
+            .map(|(key, entry)| (key, entry.rect, entry.ref_count))
+            .collect();
+        let evicted = survivors.len() < before;
+        let old_buffer = self.buffer.clone();
+        let old_size = self.size;
+        for b in &mut self.buffer {
+            *b = 0;
+        }
+        self.cursor = (0, 0);
+        self.row_height = 0;
+        for (key, rect, ref_count) in survivors {
+            // Repacking strictly fewer (or equally many) glyphs into a buffer of the same
+            // size they already fit in can't fail.
+            let (x, y) = self
+                .alloc(rect.w, rect.h)
+                .expect("repacked survivors must fit in the same-size atlas they fit before");
+            for row in 0..rect.h {
+                let src = ((rect.y + row) * old_size + rect.x) as usize;
+                let dst = ((y + row) * self.size + x) as usize;
+                self.buffer[dst..dst + rect.w as usize]
+                    .copy_from_slice(&old_buffer[src..src + rect.w as usize]);
+            }
+            // Preserve the survivor's real ref-count across the repack: it's still
+            // referenced by the same live `UiText`s as before, just at new coordinates.
+            // Zeroing it here made every surviving glyph look unreferenced the moment a
+            // second eviction ran, since non-dirty `UiText`s never touch `ref_count`
+            // again — a second eviction could then reclaim a slot a static, still-visible
+            // text was sampling, turning it into a different glyph's bitmap.
+            self.glyphs.insert(
+                key,
+                AtlasEntry {
+                    rect: AtlasRect { x, y, ..rect },
+                    ref_count,
+                },
+            );
+        }
+        self.mark_dirty(0, 0, self.size, self.size);
+        evicted
+    }
+
+    /// Uploads the atlas texture if anything has changed since the last call.
+    fn sync_texture(&mut self, loader: &Loader, tex_storage: &AssetStorage<Texture>) {
+        // `dirty_rect` pins down exactly which texels changed, but `TextureData`/
+        // `Loader::load_from_data` only expose "replace the whole asset" - there's no
+        // sub-image write entry point at this layer (that needs `Encoder` access, which
+        // belongs to the render pass, not this resource). Re-uploading the full buffer
+        // is the best this API surface allows; the bounding box is kept around mainly so
+        // a future partial-upload path has it ready without recomputing it.
+        if self.dirty_rect.take().is_none() {
+            return;
+        }
+        let meta = TextureMetadata {
+            sampler: None,
+            mip_levels: Some(1),
+            size: Some((self.size as u16, self.size as u16)),
+            dynamic: true,
+            format: Some(SurfaceType::R8),
+            channel: Some(ChannelType::Unorm),
+        };
+        let data = TextureData::U8(self.buffer.clone(), meta);
+        self.texture = Some(loader.load_from_data(data, (), tex_storage));
+    }
+}
+
+/// Controls how a `UiText`'s content is broken into lines.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LineMode {
+    /// Render the text without wrapping; it may overflow the `UiTransform`'s width. An
+    /// explicit `\n` still starts a new line.
+    Single,
+    /// Greedily word-wrap the text at whitespace so lines fit the `UiTransform`'s width.
+    /// Words are never split, so a single word wider than the width sits alone on its
+    /// line and overflows it.
+    Wrap,
+}
+
+/// Where a `UiText`'s laid-out lines are anchored within its `UiTransform`'s bounds.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Anchor {
+    TopLeft,
+    TopMiddle,
+    TopRight,
+    MiddleLeft,
+    Middle,
+    MiddleRight,
+    BottomLeft,
+    BottomMiddle,
+    BottomRight,
+}
+
+impl Anchor {
+    /// 0.0 for left, 0.5 for horizontally centered, 1.0 for right.
+    fn horizontal_bias(&self) -> f32 {
+        match *self {
+            Anchor::TopLeft | Anchor::MiddleLeft | Anchor::BottomLeft => 0.,
+            Anchor::TopMiddle | Anchor::Middle | Anchor::BottomMiddle => 0.5,
+            Anchor::TopRight | Anchor::MiddleRight | Anchor::BottomRight => 1.,
+        }
+    }
+
+    /// 0.0 for top, 0.5 for vertically centered, 1.0 for bottom.
+    fn vertical_bias(&self) -> f32 {
+        match *self {
+            Anchor::TopLeft | Anchor::TopMiddle | Anchor::TopRight => 0.,
+            Anchor::MiddleLeft | Anchor::Middle | Anchor::MiddleRight => 0.5,
+            Anchor::BottomLeft | Anchor::BottomMiddle | Anchor::BottomRight => 1.,
+        }
+    }
+}
+
+/// A single glyph of a laid-out line: which glyph to draw, where relative to the line's
+/// own start, and which input segment (`UiText` section) it came from.
+struct LineGlyph {
+    id: GlyphId,
+    x: f32,
+    segment: usize,
+}
+
+/// One line of text: word-wrapped in `LineMode::Wrap`, and in either mode ended by an
+/// explicit `\n`.
+struct Line {
+    glyphs: Vec<LineGlyph>,
+    width: f32,
+}
+
+/// Greedily lays a sequence of `(font, scale, text)` segments out into one or more `Line`s
+/// no wider than `max_width` (ignored in `LineMode::Single`), breaking on whitespace and
+/// explicit `\n`s. The pen position carries across segment boundaries, so two segments on
+/// the same line read as one continuous run; kerning is only applied between glyphs drawn
+/// with the same segment's font, since kerning pairs aren't meaningful across fonts.
+/// `embolden` widens every glyph's advance to leave room for the extra coverage a
+/// synthetic bold dilates outward.
+fn layout_lines<'f>(
+    segments: &[(&rusttype::Font<'f>, Scale, &str)],
+    line_mode: LineMode,
+    max_width: f32,
+    embolden: f32,
+) -> Vec<Line> {
+    let mut lines = vec![Line { glyphs: Vec::new(), width: 0. }];
+    let mut last: Option<(usize, GlyphId)> = None;
+
+    let mut chars = segments
+        .iter()
+        .enumerate()
+        .flat_map(|(segment, &(_, _, text))| text.chars().map(move |c| (segment, c)))
+        .peekable();
+
+    while let Some(&(segment, c)) = chars.peek() {
+        let (font, scale, _) = segments[segment];
+        if c == '\n' {
+            chars.next();
+            lines.push(Line { glyphs: Vec::new(), width: 0. });
+            last = None;
+            continue;
+        }
+        if c.is_whitespace() {
+            chars.next();
+            let line = lines.last_mut().unwrap();
+            // Swallow leading whitespace on a freshly-wrapped line.
+            if line_mode == LineMode::Wrap && line.glyphs.is_empty() {
+                last = None;
+                continue;
+            }
+            let glyph = font.glyph(c).scaled(scale);
+            let id = glyph.id();
+            let kerning = match last {
+                Some((prev_segment, prev_id)) if prev_segment == segment => {
+                    font.pair_kerning(scale, prev_id, id)
+                }
+                _ => 0.,
+            };
+            line.glyphs.push(LineGlyph { id, x: line.width + kerning, segment });
+            line.width += kerning + glyph.h_metrics().advance_width + embolden.max(0.0);
+            last = Some((segment, id));
+            continue;
+        }
+
+        // Collect the whole word (which may span a segment boundary) before deciding
+        // whether it fits, so wrapping only ever happens between words.
+        let mut word = Vec::new();
+        let mut word_width = 0.;
+        let mut word_last: Option<(usize, GlyphId)> = None;
+        while let Some(&(word_segment, wc)) = chars.peek() {
+            if wc.is_whitespace() || wc == '\n' {
+                break;
+            }
+            chars.next();
+            let (word_font, word_scale, _) = segments[word_segment];
+            let glyph = word_font.glyph(wc).scaled(word_scale);
+            let id = glyph.id();
+            let kerning = match word_last {
+                Some((prev_segment, prev_id)) if prev_segment == word_segment => {
+                    word_font.pair_kerning(word_scale, prev_id, id)
+                }
+                _ => 0.,
+            };
+            word.push(LineGlyph { id, x: word_width + kerning, segment: word_segment });
+            word_width += kerning + glyph.h_metrics().advance_width + embolden.max(0.0);
+            word_last = Some((word_segment, id));
+        }
+
+        let line = lines.last_mut().unwrap();
+        let leading_kerning = match (last, word.first()) {
+            (Some((prev_segment, prev_id)), Some(first)) if prev_segment == first.segment => {
+                let (font, scale, _) = segments[prev_segment];
+                font.pair_kerning(scale, prev_id, first.id)
+            }
+            _ => 0.,
+        };
+        if line_mode == LineMode::Wrap && !line.glyphs.is_empty()
+            && line.width + leading_kerning + word_width > max_width
+        {
+            lines.push(Line { glyphs: Vec::new(), width: 0. });
+            last = None;
+        }
+
+        let line = lines.last_mut().unwrap();
+        let base_x = if line.glyphs.is_empty() { 0. } else { line.width + leading_kerning };
+        for glyph in word {
+            line.glyphs.push(LineGlyph { id: glyph.id, x: base_x + glyph.x, segment: glyph.segment });
+        }
+        line.width = base_x + word_width;
+        last = word_last;
+    }
+
+    lines
+}
+
+/// A styled run of text within a `UiText`. Any field left as `None` falls back to the
+/// owning `UiText`'s own font/color/font_size, so a caller only needs to override what
+/// actually differs for this particular run. Consecutive sections on the same line flow
+/// together as one continuous run of text, e.g. a white "Score: " section followed by a
+/// yellow "100" section.
+pub struct TextSection {
+    /// The text of this run.
+    pub text: String,
+    /// Overrides the component's font for this run, if set.
+    pub font: Option<FontHandle>,
+    /// Overrides the component's color for this run, if set.
+    pub color: Option<[f32; 4]>,
+    /// Overrides the component's font size for this run, if set.
+    pub font_size: Option<f32>,
+}
+
+/// Synthetic style applied to every glyph of a `UiText` when no matching weight or slant
+/// exists in the loaded font itself, mirroring the fallback browsers use for a missing
+/// bold/italic face of an otherwise-matching font family.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct SyntheticStyle {
+    /// Extra coverage, in pixels, dilated outward from each glyph's edges to fake a
+    /// bolder weight. `0.0` (the default) draws the font's own weight unmodified.
+    pub embolden: f32,
+    /// Horizontal shear applied per scanline to fake an oblique/italic slant: pixels of
+    /// horizontal offset per pixel of vertical distance from the glyph's baseline. `0.0`
+    /// (the default) draws the font upright.
+    pub skew: f32,
+}
+
 /// A component used to display text in this entities UiTransform
 pub struct UiText {
-    /// The texture that text is rendered onto.  None if text isn't rendered yet.
+    /// The shared atlas texture `glyphs` sample from. None until `UiTextRenderer` has
+    /// uploaded the atlas for the first time.
     pub(crate) texture: Option<TextureHandle>,
+    /// A mesh of one quad per visible glyph, in entity-local pixel space, sampling
+    /// `texture`. Rebuilt only when the text actually changes, so glyphs that were
+    /// already on-screen don't cost anything.
+    pub(crate) mesh: Option<MeshHandle>,
+    /// The quads `mesh` was built from, one per visible glyph, sampling `texture`.
+    pub(crate) glyphs: Vec<GlyphQuad>,
+    /// The atlas slots `glyphs` currently references, kept around so the atlas can be
+    /// told when this text stops using them.
+    used_glyphs: Vec<GlyphKey>,
     /// The font used to display the text.
     font: FontHandle, //TODO: Mark this component dirty when the font is reloaded.
     /// The text being displayed
@@ -20,6 +678,24 @@ pub struct UiText {
     color: [f32; 4],
     /// The font size of the text being displayed
     font_size: f32,
+    /// Styled runs overriding `font`/`color`/`font_size` per span. When set, these are
+    /// rendered in place of `text`, which is then not drawn. `None` means the whole of `text` is rendered as a single unstyled run using the
+    /// component's own font/color/font_size.
+    sections: Option<Vec<TextSection>>,
+    /// Whether the text wraps to fit the `UiTransform`'s width or renders as a single line.
+    line_mode: LineMode,
+    /// Where the laid-out lines are anchored within the `UiTransform`'s bounds.
+    align: Anchor,
+    /// How rasterized glyph coverage is turned into alpha.
+    render_mode: FontRenderMode,
+    /// Gamma correction contrast multiplier, applied in `FontRenderMode::Alpha` and
+    /// `FontRenderMode::SubpixelAA`.
+    contrast: f32,
+    /// Gamma correction exponent, applied in `FontRenderMode::Alpha` and
+    /// `FontRenderMode::SubpixelAA`.
+    gamma: f32,
+    /// Synthetic bold/oblique style applied to every glyph.
+    synthetic: SyntheticStyle,
     /// This is true if the texture needs to be re-rendered
     dirty: bool,
 }
@@ -36,10 +712,20 @@ impl UiText {
     pub fn new(font: FontHandle, text: String, color: [f32; 4], font_size: f32) -> UiText {
         UiText {
             texture: None,
+            mesh: None,
+            glyphs: Vec::new(),
+            used_glyphs: Vec::new(),
             font,
             text,
             color,
             font_size,
+            sections: None,
+            line_mode: LineMode::Single,
+            align: Anchor::TopLeft,
+            render_mode: FontRenderMode::Alpha,
+            contrast: DEFAULT_CONTRAST,
+            gamma: DEFAULT_GAMMA,
+            synthetic: SyntheticStyle::default(),
             dirty: true,
         }
     }
@@ -93,10 +779,97 @@ impl UiText {
         self.dirty = true;
         self.font_size = size;
     }
+
+    /// Whether the text wraps to fit the `UiTransform`'s width or renders as a single line.
+    pub fn line_mode(&self) -> LineMode {
+        self.line_mode
+    }
+
+    /// Sets whether the text wraps to fit the `UiTransform`'s width or renders as a single,
+    /// unbounded line.
+    pub fn set_line_mode(&mut self, line_mode: LineMode) {
+        self.line_mode = line_mode;
+        self.dirty = true;
+    }
+
+    /// Where the laid-out lines are anchored within the `UiTransform`'s bounds.
+    pub fn align(&self) -> Anchor {
+        self.align
+    }
+
+    /// Sets where the laid-out lines are anchored within the `UiTransform`'s bounds.
+    pub fn set_align(&mut self, align: Anchor) {
+        self.align = align;
+        self.dirty = true;
+    }
+
+    /// How rasterized glyph coverage is turned into alpha.
+    pub fn render_mode(&self) -> FontRenderMode {
+        self.render_mode
+    }
+
+    /// Sets how rasterized glyph coverage is turned into alpha.
+    pub fn set_render_mode(&mut self, render_mode: FontRenderMode) {
+        self.render_mode = render_mode;
+        self.dirty = true;
+    }
+
+    /// The gamma correction `(contrast, gamma)` applied to glyph coverage in
+    /// `FontRenderMode::Alpha`/`FontRenderMode::SubpixelAA`.
+    pub fn gamma_correction(&self) -> (f32, f32) {
+        (self.contrast, self.gamma)
+    }
+
+    /// Sets the gamma correction applied to glyph coverage in
+    /// `FontRenderMode::Alpha`/`FontRenderMode::SubpixelAA`. Defaults to a contrast of
+    /// `1.0` and a gamma of `1.0 / 2.2`, undoing the display's own gamma encoding so
+    /// anti-aliased edges read at the intended weight instead of looking thin.
+    pub fn set_gamma_correction(&mut self, contrast: f32, gamma: f32) {
+        self.contrast = contrast;
+        self.gamma = gamma;
+        self.dirty = true;
+    }
+
+    /// The synthetic bold/oblique style applied to every glyph.
+    pub fn synthetic(&self) -> SyntheticStyle {
+        self.synthetic
+    }
+
+    /// Sets the synthetic bold/oblique style applied to every glyph, for fonts that don't
+    /// ship a matching bold or italic face.
+    pub fn set_synthetic(&mut self, synthetic: SyntheticStyle) {
+        self.synthetic = synthetic;
+        self.dirty = true;
+    }
+
+    /// Replaces this text with a list of independently styled `TextSection`s. Sections on
+    /// the same line flow together, so a caller can mix colors, fonts, and sizes inline
+    /// without spawning separate overlapping entities.
+    pub fn set_sections(&mut self, sections: Vec<TextSection>) {
+        self.sections = Some(sections);
+        self.dirty = true;
+    }
+
+    /// A mutable handle to this text's sections, for incremental edits. If this `UiText`
+    /// hasn't been split into sections yet, it's seeded with a single section carrying the
+    /// current `text()`, equivalent to `set_sections(vec![TextSection { text: ..,
+    /// font: None, color: None, font_size: None }])`.
+    pub fn sections_mut(&mut self) -> &mut Vec<TextSection> {
+        self.dirty = true;
+        if self.sections.is_none() {
+            let text = self.text.clone();
+            self.sections = Some(vec![
+                TextSection { text, font: None, color: None, font_size: None },
+            ]);
+        }
+        self.sections.as_mut().unwrap()
+    }
 }
 
 impl Component for UiText {
-    type Storage = DenseVecStorage<Self>;
+    // `FlaggedStorage` (rather than a plain `DenseVecStorage`) so `GlyphAtlas` can track
+    // `ComponentEvent::Removed`s and release the atlas slots a despawned entity was using.
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
 }
 
 /// This system renders `UiText`.
@@ -106,62 +879,259 @@ pub struct UiTextRenderer;
 
 impl<'a> System<'a> for UiTextRenderer {
     type SystemData = (
+        Entities<'a>,
         ReadStorage<'a, UiTransform>,
         WriteStorage<'a, UiText>,
         Fetch<'a, Loader>,
         Fetch<'a, AssetStorage<Texture>>,
+        Fetch<'a, AssetStorage<Mesh>>,
         Fetch<'a, AssetStorage<FontAsset>>,
+        FetchMut<'a, GlyphAtlas>,
     );
 
-    fn run(&mut self, (transform, mut text, loader, tex_storage, font_storage): Self::SystemData) {
-        for (transform, text) in (&transform, &mut text)
+    fn run(
+        &mut self,
+        (entities, transform, mut text, loader, tex_storage, mesh_storage, font_storage, mut atlas): Self::SystemData,
+    ) {
+        // An entity despawning (or its `UiText` being removed outright) takes its
+        // `used_glyphs` down with it before `text.used_glyphs.drain(..)` below ever runs
+        // for it, which would otherwise leak its atlas references forever. Catch that via
+        // the component's removal events instead, using `entity_glyphs` (keyed by index,
+        // since the event only carries that much) to recover what to release.
+        let reader_id = atlas.text_reader.get_or_insert_with(|| text.register_reader());
+        let removed: Vec<Index> = text
+            .channel()
+            .read(reader_id)
+            .filter_map(|event| match *event {
+                ComponentEvent::Removed(id) => Some(id),
+                _ => None,
+            })
+            .collect();
+        for id in removed {
+            if let Some(keys) = atlas.entity_glyphs.remove(&id) {
+                atlas.release_glyphs(&keys);
+            }
+        }
+
+        for (entity, transform, text) in (&entities, &transform, &mut text)
             .join()
-            .filter(|&(_transform, ref text)| text.dirty)
+            .filter(|&(_entity, _transform, ref text)| text.dirty)
         {
+            // Every run's font needs to be loaded before a layout means anything; if one
+            // isn't ready yet, try again next frame rather than rendering a partial result.
             // TODO: use `TrackedStorage`
-            if let Some(font) = font_storage.get(&text.font) {
-                text.dirty = false;
-                if (*text.text).chars().any(|c| is_combining_mark(c)) {
-                    let normalized = text.text.nfd().collect::<String>();
-                    text.text = normalized;
+            let font_handles: Vec<FontHandle> = match text.sections {
+                Some(ref sections) => sections
+                    .iter()
+                    .map(|section| section.font.clone().unwrap_or_else(|| text.font.clone()))
+                    .collect(),
+                None => vec![text.font.clone()],
+            };
+            let fonts: Option<Vec<&FontAsset>> =
+                font_handles.iter().map(|font| font_storage.get(font)).collect();
+            let fonts = match fonts {
+                Some(fonts) => fonts,
+                None => continue,
+            };
+
+            text.dirty = false;
+            if text.text.chars().any(|c| is_combining_mark(c)) {
+                text.text = text.text.nfd().collect();
+            }
+            if let Some(ref mut sections) = text.sections {
+                for section in sections.iter_mut() {
+                    if section.text.chars().any(|c| is_combining_mark(c)) {
+                        section.text = section.text.nfd().collect();
+                    }
                 }
-                let num_floats = (transform.width * transform.height) as usize * 4;
-                let mut render_buffer = vec![0.0; num_floats];
-                let height = transform.height as u32;
-                let width = transform.width as u32;
-                if text.color[3] > 0.01 {
-                    for glyph in font.0.layout(
-                        &text.text,
-                        Scale::uniform(text.font_size),
-                        Point::<f32> { x: 0., y: 0. },
-                    ) {
-                        let position = glyph.position();
-                        let pos_x = position.x as u32;
-                        glyph.draw(|x, y, v| {
-                            if v > 0.01 {
-                                let x = x + pos_x;
-                                if x < width && y < height {
-                                    let start = ((x + y * width) * 4) as usize;
-                                    render_buffer[start] = text.color[0];
-                                    render_buffer[start + 1] = text.color[1];
-                                    render_buffer[start + 2] = text.color[2];
-                                    render_buffer[start + 3] = text.color[3] * v;
-                                }
-                            }
+            }
+
+            // The runs of text to lay out, in order: either the explicit sections, each
+            // falling back to the component's own font/color/font_size, or (the common
+            // case) the whole of `text` as a single implicit run.
+            let runs: Vec<(FontHandle, [f32; 4], f32, String)> = match text.sections {
+                Some(ref sections) => sections
+                    .iter()
+                    .zip(font_handles.iter())
+                    .map(|(section, font)| {
+                        (
+                            font.clone(),
+                            section.color.unwrap_or(text.color),
+                            section.font_size.unwrap_or(text.font_size),
+                            section.text.clone(),
+                        )
+                    })
+                    .collect(),
+                None => vec![(text.font.clone(), text.color, text.font_size, text.text.clone())],
+            };
+
+            let stale_glyphs: Vec<GlyphKey> = text.used_glyphs.drain(..).collect();
+            atlas.release_glyphs(&stale_glyphs);
+
+            let mut glyphs = Vec::new();
+            let mut used_glyphs = Vec::new();
+
+            let segments: Vec<(&rusttype::Font<'_>, Scale, &str)> = fonts
+                .iter()
+                .zip(runs.iter())
+                .map(|(font, &(_, _, size, ref run_text))| {
+                    (&font.0, Scale::uniform(size), run_text.as_str())
+                })
+                .collect();
+
+            if segments.is_empty() {
+                // `set_sections(vec![])` (or emptying `sections_mut()`) is valid,
+                // type-correct usage that means "no text"; there's no run to measure a
+                // line height from, so just clear this `UiText`'s render state instead of
+                // indexing into the (also empty) per-segment metrics below.
+                text.mesh = None;
+                text.glyphs = Vec::new();
+                text.used_glyphs = Vec::new();
+                atlas.entity_glyphs.insert(entity.id(), Vec::new());
+                continue;
+            }
+
+            let v_metrics: Vec<_> = segments.iter().map(|&(font, scale, _)| font.v_metrics(scale)).collect();
+            let default_line_height =
+                v_metrics[0].ascent - v_metrics[0].descent + v_metrics[0].line_gap;
+
+            let lines = layout_lines(
+                &segments,
+                text.line_mode,
+                transform.width,
+                text.synthetic.embolden,
+            );
+
+            // Lines mixing run sizes grow to fit the tallest run actually placed on them.
+            let line_heights: Vec<f32> = lines
+                .iter()
+                .map(|line| {
+                    line.glyphs
+                        .iter()
+                        .map(|g| {
+                            let vm = v_metrics[g.segment];
+                            vm.ascent - vm.descent + vm.line_gap
+                        })
+                        .fold(0., f32::max)
+                })
+                .map(|h| if h > 0. { h } else { default_line_height })
+                .collect();
+            let line_ascents: Vec<f32> = lines
+                .iter()
+                .map(|line| line.glyphs.iter().map(|g| v_metrics[g.segment].ascent).fold(0., f32::max))
+                .map(|a| if a > 0. { a } else { v_metrics[0].ascent })
+                .collect();
+
+            let block_height: f32 = line_heights.iter().sum();
+            let block_y = (transform.height - block_height) * text.align.vertical_bias();
+
+            let mut line_y = block_y;
+            for (i, line) in lines.iter().enumerate() {
+                let line_x = (transform.width - line.width) * text.align.horizontal_bias();
+                let baseline_y = line_y + line_ascents[i];
+                for line_glyph in &line.glyphs {
+                    let color = runs[line_glyph.segment].1;
+                    let size = runs[line_glyph.segment].2;
+                    if color[3] <= 0.01 {
+                        // Invisible run: still occupies its layout width, just draws nothing.
+                        continue;
+                    }
+                    let (font, scale, _) = segments[line_glyph.segment];
+                    let positioned = font.glyph(line_glyph.id).scaled(scale).positioned(Point {
+                        x: line_x + line_glyph.x,
+                        y: baseline_y,
+                    });
+                    let bb = match positioned.pixel_bounding_box() {
+                        Some(bb) => bb,
+                        None => continue,
+                    };
+                    // Must match the x actually handed to `.positioned()` above, not just
+                    // `line_glyph.x` on its own - otherwise two glyphs landing on the same
+                    // true subpixel phase but different `line_x` offsets miss each other in
+                    // the cache, while glyphs that genuinely differ can collide on one that
+                    // happens to cancel out the same way.
+                    let subpixel = (
+                        ((line_x + line_glyph.x).fract() * 255.0) as u8,
+                        (baseline_y.fract() * 255.0) as u8,
+                    );
+                    let key = GlyphKey {
+                        font: runs[line_glyph.segment].0.clone(),
+                        glyph: line_glyph.id,
+                        size_bits: size.to_bits(),
+                        subpixel,
+                        mode: text.render_mode,
+                        contrast_bits: text.contrast.to_bits(),
+                        gamma_bits: text.gamma.to_bits(),
+                        luminance_bucket: luminance_bucket(color),
+                        embolden_bits: text.synthetic.embolden.to_bits(),
+                        skew_bits: text.synthetic.skew.to_bits(),
+                    };
+                    let rect = atlas.rect_for(
+                        key.clone(),
+                        &positioned,
+                        text.render_mode,
+                        text.contrast,
+                        text.gamma,
+                        text.synthetic.embolden,
+                        text.synthetic.skew,
+                    );
+                    if let Some(rect) = rect {
+                        if let Some(entry) = atlas.glyphs.get_mut(&key) {
+                            entry.ref_count += 1;
+                        }
+                        used_glyphs.push(key);
+                        glyphs.push(GlyphQuad {
+                            pos: [
+                                bb.min.x as f32 + rect.offset_x,
+                                bb.min.y as f32 + rect.offset_y,
+                            ],
+                            size: [rect.w as f32, rect.h as f32],
+                            uv_min: [
+                                rect.x as f32 / atlas.size as f32,
+                                rect.y as f32 / atlas.size as f32,
+                            ],
+                            uv_max: [
+                                (rect.x + rect.w) as f32 / atlas.size as f32,
+                                (rect.y + rect.h) as f32 / atlas.size as f32,
+                            ],
+                            color,
                         });
                     }
                 }
-                let meta = TextureMetadata {
-                    sampler: None,
-                    mip_levels: Some(1),
-                    size: Some((transform.width as u16, transform.height as u16)),
-                    dynamic: false,
-                    format: Some(SurfaceType::R32_G32_B32_A32),
-                    channel: Some(ChannelType::Float),
-                };
-                let data = TextureData::F32(render_buffer, meta);
-                text.texture = Some(loader.load_from_data(data, (), &tex_storage));
+                line_y += line_heights[i];
             }
+
+            text.mesh = if glyphs.is_empty() {
+                None
+            } else {
+                let mut verts = Vec::with_capacity(glyphs.len() * 6);
+                for glyph in &glyphs {
+                    push_glyph_quad(&mut verts, glyph);
+                }
+                Some(loader.load_from_data(verts, (), &mesh_storage))
+            };
+            text.glyphs = glyphs;
+            atlas.entity_glyphs.insert(entity.id(), used_glyphs.clone());
+            text.used_glyphs = used_glyphs;
+        }
+
+        atlas.sync_texture(&loader, &tex_storage);
+        for text in (&mut text).join() {
+            text.texture = atlas.texture().cloned();
         }
     }
 }
+
+/// Appends the two triangles making up `glyph`'s quad, in entity-local pixel space, to
+/// `verts`.
+fn push_glyph_quad(verts: &mut Vec<PosTex>, glyph: &GlyphQuad) {
+    let [x, y] = glyph.pos;
+    let [w, h] = glyph.size;
+    let [u0, v0] = glyph.uv_min;
+    let [u1, v1] = glyph.uv_max;
+    let tl = PosTex { position: [x, y, 0.], tex_coord: [u0, v0] };
+    let tr = PosTex { position: [x + w, y, 0.], tex_coord: [u1, v0] };
+    let br = PosTex { position: [x + w, y + h, 0.], tex_coord: [u1, v1] };
+    let bl = PosTex { position: [x, y + h, 0.], tex_coord: [u0, v1] };
+    verts.extend_from_slice(&[tl, tr, br, tl, br, bl]);
+}